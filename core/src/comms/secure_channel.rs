@@ -1,4 +1,7 @@
+use std::fmt;
+
 use chrono;
+use zeroize::Zeroize;
 
 use opcua_types::*;
 
@@ -9,13 +12,402 @@ use crypto::hash;
 use comms::{SecurityHeader, SymmetricSecurityHeader, AsymmetricSecurityHeader, MESSAGE_CHUNK_HEADER_SIZE, SEQUENCE_HEADER_SIZE};
 use comms::message_chunk::MessageChunkType;
 
+/// Per Part 6, a sequence number that would exceed this value wraps back to something below
+/// `SEQUENCE_NUMBER_WRAP_AROUND_LIMIT` instead of overflowing.
+const SEQUENCE_NUMBER_WRAP_AROUND: UInt32 = 4_294_966_271;
+/// Sequence numbers wrap around to a value below this limit
+const SEQUENCE_NUMBER_WRAP_AROUND_LIMIT: UInt32 = 1024;
+
+/// Writes a sequence number into the first 4 bytes of the sequence header at `offset`
+fn write_sequence_number(dst: &mut [u8], offset: usize, value: UInt32) {
+    dst[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Reads a sequence number from the first 4 bytes of the sequence header at `offset`
+fn read_sequence_number(src: &[u8], offset: usize) -> UInt32 {
+    UInt32::from_le_bytes([src[offset], src[offset + 1], src[offset + 2], src[offset + 3]])
+}
+
+/// Fault-injection hooks for exercising `SecureChannel`'s error handling in unit tests, modelled
+/// on open62541's `UA_ENABLE_UNIT_TEST_FAILURE_HOOKS`. Under the default build these compile out
+/// entirely; enabling the `testing` feature lets a test force `verify`/`decrypt` to fail with a
+/// chosen `StatusCode` without needing a malicious peer to produce genuinely corrupt data.
+#[cfg(feature = "testing")]
+pub mod testing {
+    use std::cell::Cell;
+
+    use opcua_types::StatusCode;
+
+    thread_local! {
+        static VERIFY_FAILURE: Cell<Option<StatusCode>> = Cell::new(None);
+        static DECRYPT_FAILURE: Cell<Option<StatusCode>> = Cell::new(None);
+    }
+
+    /// Forces every `verify` call on this thread to return `status_code` instead of checking
+    /// the signature, until this is called again. Pass `None` to restore normal verification.
+    pub fn set_verify_failure(status_code: Option<StatusCode>) {
+        VERIFY_FAILURE.with(|f| f.set(status_code));
+    }
+
+    /// Forces every `decrypt` call on this thread to return `status_code` instead of
+    /// decrypting, until this is called again. Pass `None` to restore normal decryption.
+    pub fn set_decrypt_failure(status_code: Option<StatusCode>) {
+        DECRYPT_FAILURE.with(|f| f.set(status_code));
+    }
+
+    pub(super) fn verify_failure() -> Option<StatusCode> {
+        VERIFY_FAILURE.with(|f| f.get())
+    }
+
+    pub(super) fn decrypt_failure() -> Option<StatusCode> {
+        DECRYPT_FAILURE.with(|f| f.get())
+    }
+}
+
+/// A `SecurityPolicyProvider` supplies the cryptographic operations that a `SecureChannel`
+/// needs in order to sign, verify, encrypt and decrypt chunks, plus the sizing information
+/// used to compute padding. `SecureChannel` holds one of these as a boxed trait object and
+/// delegates to it rather than matching on a closed set of `SecurityPolicy` variants, so a
+/// caller can supply a custom policy at runtime without `SecureChannel` ever needing to know
+/// about it. This mirrors the function-pointer based policy plugin mechanism used by
+/// open62541's `ua_securechannel.c`.
+pub trait SecurityPolicyProvider: fmt::Debug {
+    /// The security policy URI that identifies this provider, e.g.
+    /// "http://opcfoundation.org/UA/SecurityPolicy#Basic256Sha256"
+    fn policy_uri(&self) -> UAString;
+
+    /// True for the `None` policy, i.e. no signing and no encryption
+    fn is_none(&self) -> bool {
+        false
+    }
+
+    /// Signs `src` using the supplied symmetric signing key, writing the result into `signature`
+    fn symmetric_sign(&self, key: &[u8], src: &[u8], signature: &mut [u8]) -> Result<(), StatusCode>;
+
+    /// Verifies `signature` over `src` using the supplied symmetric signing key
+    fn symmetric_verify(&self, key: &[u8], src: &[u8], signature: &[u8]) -> Result<bool, StatusCode>;
+
+    /// Encrypts `src` into `dst` using the supplied symmetric encryption key and IV
+    fn symmetric_encrypt(&self, key: &AesKey, iv: &[u8], src: &[u8], dst: &mut [u8]) -> Result<(), StatusCode>;
+
+    /// Decrypts `src` into `dst` using the supplied symmetric encryption key and IV
+    fn symmetric_decrypt(&self, key: &AesKey, iv: &[u8], src: &[u8], dst: &mut [u8]) -> Result<(), StatusCode>;
+
+    /// Derives a (signing key, encryption key, IV) triple from the secret / seed nonce pair
+    fn make_secure_channel_keys(&self, secret: &[u8], seed: &[u8]) -> (Vec<u8>, AesKey, Vec<u8>);
+
+    /// Size in bytes of a symmetric signature produced by this policy
+    fn symmetric_signature_size(&self) -> usize;
+
+    /// Plain text block size used for padding calculations
+    fn plain_block_size(&self) -> usize;
+
+    /// Cipher text block size used for padding calculations
+    fn cipher_block_size(&self) -> usize;
+
+    /// Size in bytes of the symmetric key / nonce used by this policy
+    fn symmetric_key_size(&self) -> usize;
+
+    /// Signs `src` for the OpenSecureChannel exchange, before any symmetric key material has
+    /// been derived. RSA policies sign with our RSA private key; ECC policies sign with our
+    /// long-term EC private key via `ecdsa_sign`.
+    fn asymmetric_sign(&self, private_key: &PKey, src: &[u8], signature: &mut [u8]) -> Result<(), StatusCode>;
+
+    /// Verifies an asymmetric `signature` over `src` using the peer's public key. RSA policies
+    /// verify with the peer's RSA public key; ECC policies verify via `ecdsa_verify`.
+    fn asymmetric_verify(&self, their_cert: &X509, src: &[u8], signature: &[u8]) -> Result<bool, StatusCode>;
+
+    /// Encrypts `src` into `dst` using the peer's RSA public key, returning the number of bytes
+    /// written to `dst`. Only the RSA policies support this: the ECC policies have no ECIES/ECDH
+    /// key transport implemented here, so `OpenSecureChannel` under an ECC policy is only usable
+    /// with `MessageSecurityMode::Sign`, never `SignAndEncrypt`.
+    fn asymmetric_encrypt(&self, their_cert: &X509, src: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode>;
+
+    /// Decrypts `src` into `dst` using our RSA private key, returning the number of bytes written
+    /// to `dst`. See `asymmetric_encrypt` for why this is RSA-only.
+    fn asymmetric_decrypt(&self, private_key: &PKey, src: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode>;
+
+    /// Size in bytes of an asymmetric signature produced with the supplied private key
+    fn asymmetric_signature_size(&self, private_key: &PKey) -> usize {
+        private_key.size()
+    }
+
+    /// Largest number of plain text bytes that fit in one RSA block for the supplied key,
+    /// accounting for the policy's signing/encryption padding overhead
+    fn asymmetric_plain_text_block_size(&self, private_key: &PKey) -> usize;
+
+    /// RSA modulus size in bytes for the supplied key, i.e. the encrypted block size
+    fn asymmetric_cipher_text_block_size(&self, private_key: &PKey) -> usize {
+        private_key.size()
+    }
+
+    /// True for the ECC-based policies (ECDSA over NIST curves), which sign every chunk with the
+    /// channel's long-term EC key pair rather than a PRF-derived HMAC key
+    fn is_ecc(&self) -> bool {
+        false
+    }
+
+    /// Computes an ECDSA signature over `src` using our EC private key, returning the raw
+    /// `r || s` fixed-width signature (no ASN.1/DER framing)
+    fn ecdsa_sign(&self, private_key: &PKey, src: &[u8]) -> Result<Vec<u8>, StatusCode> {
+        let _ = (private_key, src);
+        error!("Cannot ECDSA sign, {:?} is not an ECC security policy", self);
+        Err(BAD_SECURITY_POLICY_REJECTED)
+    }
+
+    /// Verifies a raw `r || s` ECDSA signature over `src` using the peer's EC public key
+    fn ecdsa_verify(&self, their_cert: &X509, src: &[u8], signature: &[u8]) -> Result<bool, StatusCode> {
+        let _ = (their_cert, src, signature);
+        error!("Cannot ECDSA verify, {:?} is not an ECC security policy", self);
+        Err(BAD_SECURITY_POLICY_REJECTED)
+    }
+}
+
+impl SecurityPolicyProvider for SecurityPolicy {
+    fn policy_uri(&self) -> UAString {
+        UAString::from(self.to_uri())
+    }
+
+    fn is_none(&self) -> bool {
+        *self == SecurityPolicy::None
+    }
+
+    fn symmetric_sign(&self, key: &[u8], src: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
+        match *self {
+            SecurityPolicy::Basic128Rsa15 => {
+                // HMAC SHA-1
+                hash::hmac_sha1(key, src, signature)
+            }
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {
+                // HMAC SHA-256
+                hash::hmac_sha256(key, src, signature)
+            }
+            _ => {
+                error!("Cannot sign, unsupported security policy {:?}", self);
+                Err(BAD_SECURITY_POLICY_REJECTED)
+            }
+        }
+    }
+
+    fn symmetric_verify(&self, key: &[u8], src: &[u8], signature: &[u8]) -> Result<bool, StatusCode> {
+        match *self {
+            SecurityPolicy::Basic128Rsa15 => {
+                // HMAC SHA-1
+                Ok(hash::verify_hmac_sha1(key, src, signature))
+            }
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {
+                // HMAC SHA-256
+                Ok(hash::verify_hmac_sha256(key, src, signature))
+            }
+            _ => {
+                error!("Cannot verify, unsupported security policy {:?}", self);
+                Err(BAD_SECURITY_POLICY_REJECTED)
+            }
+        }
+    }
+
+    fn symmetric_encrypt(&self, key: &AesKey, iv: &[u8], src: &[u8], dst: &mut [u8]) -> Result<(), StatusCode> {
+        key.encrypt(src, iv, dst).map_err(|err| {
+            error!("Cannot encrypt data, {}", err);
+            BAD_ENCODING_ERROR
+        })
+    }
+
+    fn symmetric_decrypt(&self, key: &AesKey, iv: &[u8], src: &[u8], dst: &mut [u8]) -> Result<(), StatusCode> {
+        key.decrypt(src, iv, dst).map_err(|err| {
+            error!("Cannot decrypt data, {}", err);
+            BAD_DECODING_ERROR
+        })
+    }
+
+    fn make_secure_channel_keys(&self, secret: &[u8], seed: &[u8]) -> (Vec<u8>, AesKey, Vec<u8>) {
+        if self.is_ecc() {
+            // The ECC policies have no RSA-based PRF input to drive the Part 6 P_SHA key
+            // schedule, so key material is derived with an HKDF-style expansion (RFC 5869) of
+            // the nonces instead.
+            let signing_key_size = SecurityPolicy::symmetric_signature_size(self);
+            let encrypting_key_size = SecurityPolicy::symmetric_key_size(self);
+            let encrypting_block_size = SecurityPolicy::plain_block_size(self);
+            let okm_len = signing_key_size + encrypting_key_size + encrypting_block_size;
+            let okm = match *self {
+                SecurityPolicy::EccNistP256 => hash::hkdf_sha256_expand(secret, seed, okm_len),
+                SecurityPolicy::EccNistP384 => hash::hkdf_sha384_expand(secret, seed, okm_len),
+                _ => unreachable!(),
+            };
+            let signing_key = okm[..signing_key_size].to_vec();
+            let encrypting_key = AesKey::new(*self, &okm[signing_key_size..signing_key_size + encrypting_key_size]);
+            let iv = okm[signing_key_size + encrypting_key_size..].to_vec();
+            (signing_key, encrypting_key, iv)
+        } else {
+            SecurityPolicy::make_secure_channel_keys(self, secret, seed)
+        }
+    }
+
+    fn symmetric_signature_size(&self) -> usize {
+        SecurityPolicy::symmetric_signature_size(self)
+    }
+
+    fn plain_block_size(&self) -> usize {
+        SecurityPolicy::plain_block_size(self)
+    }
+
+    fn cipher_block_size(&self) -> usize {
+        SecurityPolicy::cipher_block_size(self)
+    }
+
+    fn symmetric_key_size(&self) -> usize {
+        SecurityPolicy::symmetric_key_size(self)
+    }
+
+    fn asymmetric_sign(&self, private_key: &PKey, src: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
+        match *self {
+            // RSASSA-PKCS1-v1_5 with SHA-1
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 => {
+                hash::sign_sha1_pkcs15(private_key, src, signature)
+            }
+            // RSASSA-PKCS1-v1_5 with SHA-256
+            SecurityPolicy::Basic256Sha256 => {
+                hash::sign_sha256_pkcs15(private_key, src, signature)
+            }
+            // ECC policies have no RSA private key to sign with here; they use ECDSA over our
+            // long-term EC key pair, same as the symmetric per-chunk signature.
+            SecurityPolicy::EccNistP256 | SecurityPolicy::EccNistP384 => {
+                let ecdsa_signature = self.ecdsa_sign(private_key, src)?;
+                signature.copy_from_slice(&ecdsa_signature);
+                Ok(())
+            }
+            _ => {
+                error!("Cannot asymmetric sign, unsupported security policy {:?}", self);
+                Err(BAD_SECURITY_POLICY_REJECTED)
+            }
+        }
+    }
+
+    fn asymmetric_verify(&self, their_cert: &X509, src: &[u8], signature: &[u8]) -> Result<bool, StatusCode> {
+        if self.is_ecc() {
+            return self.ecdsa_verify(their_cert, src, signature);
+        }
+        let public_key = their_cert.public_key().map_err(|err| {
+            error!("Cannot obtain public key from certificate, {}", err);
+            BAD_CERTIFICATE_INVALID
+        })?;
+        match *self {
+            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 => {
+                Ok(hash::verify_sha1_pkcs15(&public_key, src, signature))
+            }
+            SecurityPolicy::Basic256Sha256 => {
+                Ok(hash::verify_sha256_pkcs15(&public_key, src, signature))
+            }
+            _ => {
+                error!("Cannot asymmetric verify, unsupported security policy {:?}", self);
+                Err(BAD_SECURITY_POLICY_REJECTED)
+            }
+        }
+    }
+
+    fn asymmetric_encrypt(&self, their_cert: &X509, src: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
+        let public_key = their_cert.public_key().map_err(|err| {
+            error!("Cannot obtain public key from certificate, {}", err);
+            BAD_CERTIFICATE_INVALID
+        })?;
+        let result = match *self {
+            // RSAES-PKCS1-v1_5
+            SecurityPolicy::Basic128Rsa15 => public_key.public_encrypt(src, dst, RsaPadding::Pkcs1),
+            // RSAES-OAEP (SHA-1)
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => public_key.public_encrypt(src, dst, RsaPadding::OaepSha1),
+            // ECC policies have no ECIES/ECDH key transport implemented here, so they cannot
+            // take part in an encrypted OpenSecureChannel exchange -- only MessageSecurityMode::Sign
+            // is supported for them.
+            SecurityPolicy::EccNistP256 | SecurityPolicy::EccNistP384 => {
+                error!("Cannot asymmetric encrypt, {:?} only supports MessageSecurityMode::Sign for OpenSecureChannel", self);
+                return Err(BAD_SECURITY_POLICY_REJECTED);
+            }
+            _ => {
+                error!("Cannot asymmetric encrypt, unsupported security policy {:?}", self);
+                return Err(BAD_SECURITY_POLICY_REJECTED);
+            }
+        };
+        result.map_err(|err| {
+            error!("Cannot encrypt data, {}", err);
+            BAD_ENCODING_ERROR
+        })
+    }
+
+    fn asymmetric_decrypt(&self, private_key: &PKey, src: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
+        let result = match *self {
+            SecurityPolicy::Basic128Rsa15 => private_key.private_decrypt(src, dst, RsaPadding::Pkcs1),
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => private_key.private_decrypt(src, dst, RsaPadding::OaepSha1),
+            // See asymmetric_encrypt -- ECC policies have no decrypt side to this either.
+            SecurityPolicy::EccNistP256 | SecurityPolicy::EccNistP384 => {
+                error!("Cannot asymmetric decrypt, {:?} only supports MessageSecurityMode::Sign for OpenSecureChannel", self);
+                return Err(BAD_SECURITY_POLICY_REJECTED);
+            }
+            _ => {
+                error!("Cannot asymmetric decrypt, unsupported security policy {:?}", self);
+                return Err(BAD_SECURITY_POLICY_REJECTED);
+            }
+        };
+        result.map_err(|err| {
+            error!("Cannot decrypt data, {}", err);
+            BAD_DECODING_ERROR
+        })
+    }
+
+    fn asymmetric_plain_text_block_size(&self, private_key: &PKey) -> usize {
+        let modulus_size = private_key.size();
+        match *self {
+            // RSAES-PKCS1-v1_5 overhead is 11 bytes
+            SecurityPolicy::Basic128Rsa15 => modulus_size - 11,
+            // RSAES-OAEP (SHA-1) overhead is 42 bytes
+            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => modulus_size - 42,
+            _ => modulus_size,
+        }
+    }
+
+    fn is_ecc(&self) -> bool {
+        match *self {
+            SecurityPolicy::EccNistP256 | SecurityPolicy::EccNistP384 => true,
+            _ => false,
+        }
+    }
+
+    fn ecdsa_sign(&self, private_key: &PKey, src: &[u8]) -> Result<Vec<u8>, StatusCode> {
+        match *self {
+            // ECDSA over SHA-256, raw r || s as two 32-byte big-endian integers
+            SecurityPolicy::EccNistP256 => hash::ecdsa_sign_p256(private_key, src),
+            // ECDSA over SHA-384, raw r || s as two 48-byte big-endian integers
+            SecurityPolicy::EccNistP384 => hash::ecdsa_sign_p384(private_key, src),
+            _ => {
+                error!("Cannot ECDSA sign, unsupported security policy {:?}", self);
+                Err(BAD_SECURITY_POLICY_REJECTED)
+            }
+        }
+    }
+
+    fn ecdsa_verify(&self, their_cert: &X509, src: &[u8], signature: &[u8]) -> Result<bool, StatusCode> {
+        let public_key = their_cert.public_key().map_err(|err| {
+            error!("Cannot obtain public key from certificate, {}", err);
+            BAD_CERTIFICATE_INVALID
+        })?;
+        match *self {
+            SecurityPolicy::EccNistP256 => Ok(hash::ecdsa_verify_p256(&public_key, src, signature)),
+            SecurityPolicy::EccNistP384 => Ok(hash::ecdsa_verify_p384(&public_key, src, signature)),
+            _ => {
+                error!("Cannot ECDSA verify, unsupported security policy {:?}", self);
+                Err(BAD_SECURITY_POLICY_REJECTED)
+            }
+        }
+    }
+}
+
 /// Holds all of the security information related to this session
 #[derive(Debug)]
 pub struct SecureChannel {
     /// The security mode for the connection, None, Sign, SignAndEncrypt
     pub security_mode: MessageSecurityMode,
-    /// The security policy for the connection, None or Encryption/Signing settings
-    pub security_policy: SecurityPolicy,
+    /// The security policy provider for the connection. Boxed so that callers can register a
+    /// custom `SecurityPolicyProvider` without `SecureChannel` needing to know its concrete type.
+    pub security_policy: Box<dyn SecurityPolicyProvider>,
     /// Secure channel id
     pub secure_channel_id: UInt32,
     /// Token creation time.
@@ -28,12 +420,20 @@ pub struct SecureChannel {
     pub nonce: Vec<u8>,
     /// Their nonce provided by open secure channel
     pub their_nonce: Vec<u8>,
+    /// Our certificate
+    pub cert: Option<X509>,
+    /// Our private key, used to sign / decrypt the asymmetric OpenSecureChannel exchange
+    pub private_key: Option<PKey>,
     /// Their certificate
     pub their_cert: Option<X509>,
     /// Symmetric Signing Key, Encrypt Key, IV
     pub keys: Option<(Vec<u8>, AesKey, Vec<u8>)>,
     /// Symmetric Signing Key, Decrypt Key, IV
     pub their_keys: Option<(Vec<u8>, AesKey, Vec<u8>)>,
+    /// The last sequence number sent by this end, used to generate the next one
+    pub last_sent_sequence_number: Option<UInt32>,
+    /// The last sequence number received from the peer, used to validate the next one
+    pub last_received_sequence_number: Option<UInt32>,
 }
 
 impl SecureChannel {
@@ -41,7 +441,7 @@ impl SecureChannel {
         // Invalid secure channel info by default
         SecureChannel {
             security_mode: MessageSecurityMode::None,
-            security_policy: SecurityPolicy::None,
+            security_policy: Box::new(SecurityPolicy::None),
             secure_channel_id: 0,
             token_id: 0,
             token_created_at: DateTime::now(),
@@ -49,15 +449,25 @@ impl SecureChannel {
             nonce: Vec::with_capacity(64),
             their_nonce: Vec::with_capacity(64),
             keys: None,
+            cert: None,
+            private_key: None,
             their_cert: None,
             their_keys: None,
+            last_sent_sequence_number: None,
+            last_received_sequence_number: None,
         }
     }
 
     pub fn make_security_header(&self, message_type: MessageChunkType) -> SecurityHeader {
         match message_type {
             MessageChunkType::OpenSecureChannel => {
-                SecurityHeader::Asymmetric(AsymmetricSecurityHeader::none())
+                if self.security_policy.is_none() {
+                    SecurityHeader::Asymmetric(AsymmetricSecurityHeader::none())
+                } else {
+                    let sender_certificate = self.cert.as_ref().map_or(ByteString::null(), |cert| cert.as_byte_string());
+                    let receiver_certificate_thumbprint = self.their_cert.as_ref().map_or(ByteString::null(), |cert| cert.thumbprint());
+                    SecurityHeader::Asymmetric(AsymmetricSecurityHeader::new(self.security_policy.policy_uri().as_ref(), sender_certificate, receiver_certificate_thumbprint))
+                }
             }
             _ => {
                 SecurityHeader::Symmetric(SymmetricSecurityHeader {
@@ -69,6 +479,8 @@ impl SecureChannel {
 
     /// Creates a nonce for the connection. The nonce should be the same size as the symmetric key
     pub fn create_random_nonce(&mut self) {
+        // Scrub the previous nonce before it's replaced
+        self.nonce.zeroize();
         if self.signing_enabled() || self.encryption_enabled() {
             use rand::{self, Rng};
             let mut rng = rand::thread_rng();
@@ -85,6 +497,8 @@ impl SecureChannel {
             if (self.signing_enabled() || self.encryption_enabled()) && their_nonce.len() != self.security_policy.symmetric_key_size() {
                 Err(BAD_NONCE_INVALID)
             } else {
+                // Scrub the previous nonce before it's replaced
+                self.their_nonce.zeroize();
                 self.their_nonce = their_nonce.to_vec();
                 Ok(())
             }
@@ -93,27 +507,43 @@ impl SecureChannel {
         }
     }
 
+    /// Overwrites every byte of the current signing keys, encryption keys and IVs with zeros
+    /// using a method the compiler cannot optimize away, so that renewing a token doesn't leave
+    /// the old key material lingering in freed heap memory.
+    fn zeroize_keys(&mut self) {
+        if let Some((ref mut signing_key, ref mut encrypt_key, ref mut iv)) = self.keys {
+            signing_key.zeroize();
+            encrypt_key.zeroize();
+            iv.zeroize();
+        }
+        if let Some((ref mut signing_key, ref mut decrypt_key, ref mut iv)) = self.their_keys {
+            signing_key.zeroize();
+            decrypt_key.zeroize();
+            iv.zeroize();
+        }
+    }
+
     /// Part 6
-    /// 6.7.5 
+    /// 6.7.5
     /// Deriving keys Once the SecureChannel is established the Messages are signed and encrypted with
-    /// keys derived from the Nonces exchanged in the OpenSecureChannel call. These keys are derived by passing the Nonces to a pseudo-random function which produces a sequence of bytes from a set of inputs. A pseudo-random function is represented by the following function declaration: 
+    /// keys derived from the Nonces exchanged in the OpenSecureChannel call. These keys are derived by passing the Nonces to a pseudo-random function which produces a sequence of bytes from a set of inputs. A pseudo-random function is represented by the following function declaration:
     ///
     /// ```c++
     /// Byte[] PRF( Byte[] secret,  Byte[] seed,  Int32 length,  Int32 offset)
     /// ```
     ///
-    /// Where length is the number of bytes to return and offset is a number of bytes from the beginning of the sequence. 
+    /// Where length is the number of bytes to return and offset is a number of bytes from the beginning of the sequence.
     ///
     /// The lengths of the keys that need to be generated depend on the SecurityPolicy used for the channel.
-    /// The following information is specified by the SecurityPolicy: 
+    /// The following information is specified by the SecurityPolicy:
     ///
     /// a) SigningKeyLength (from the DerivedSignatureKeyLength);
     /// b) EncryptingKeyLength (implied by the SymmetricEncryptionAlgorithm);
     /// c) EncryptingBlockSize (implied by the SymmetricEncryptionAlgorithm).
     ///
-    /// The parameters passed to the pseudo random function are specified in Table 33. 
+    /// The parameters passed to the pseudo random function are specified in Table 33.
     ///
-    /// Table 33 – Cryptography key generation parameters 
+    /// Table 33 – Cryptography key generation parameters
     ///
     /// Key | Secret | Seed | Length | Offset
     /// ClientSigningKey | ServerNonce | ClientNonce | SigningKeyLength | 0
@@ -122,15 +552,57 @@ impl SecureChannel {
     /// ServerSigningKey | ClientNonce | ServerNonce | SigningKeyLength | 0
     /// ServerEncryptingKey | ClientNonce | ServerNonce | EncryptingKeyLength | SigningKeyLength
     /// ServerInitializationVector | ClientNonce | ServerNonce | EncryptingBlockSize | SigningKeyLength + EncryptingKeyLength
-    ///  
+    ///
     /// The Client keys are used to secure Messages sent by the Client. The Server keys
     /// are used to secure Messages sent by the Server.
-    /// 
+    ///
+    /// For the ECC-based security policies the above PRF is replaced by an HKDF-style expansion
+    /// of the nonces; that branch lives inside `SecurityPolicyProvider::make_secure_channel_keys`
+    /// so this method doesn't need to know which key schedule a given policy uses.
     pub fn derive_keys(&mut self) {
+        // Scrub the previous key set before it's replaced
+        self.zeroize_keys();
         self.keys = Some(self.security_policy.make_secure_channel_keys(&self.nonce, &self.their_nonce));
         debug!("Derived our keys = {:?}", self.keys);
         self.their_keys = Some(self.security_policy.make_secure_channel_keys(&self.their_nonce, &self.nonce));
         debug!("Derived their keys = {:?}", self.their_keys);
+        // A fresh set of keys starts a fresh sequence number range
+        self.last_sent_sequence_number = None;
+        self.last_received_sequence_number = None;
+    }
+
+    /// Returns the next sequence number to send, implementing the Part 6 wrap-around rule:
+    /// sequence numbers increase by one per chunk up to and including
+    /// `SEQUENCE_NUMBER_WRAP_AROUND`; the number after that would exceed it, so it wraps to a
+    /// small value below `SEQUENCE_NUMBER_WRAP_AROUND_LIMIT` instead.
+    pub fn next_sequence_number(&mut self) -> UInt32 {
+        let next = match self.last_sent_sequence_number {
+            Some(n) if n >= SEQUENCE_NUMBER_WRAP_AROUND => 1,
+            Some(n) => n + 1,
+            None => 1,
+        };
+        self.last_sent_sequence_number = Some(next);
+        next
+    }
+
+    /// Validates a received sequence number against the last one seen, implementing the same
+    /// wrap-around rule as `next_sequence_number`. A received number is valid only if it equals
+    /// the previous number plus one, except that once the previous number is at or above
+    /// `SEQUENCE_NUMBER_WRAP_AROUND` the receiver must accept any value below
+    /// `SEQUENCE_NUMBER_WRAP_AROUND_LIMIT` as the wrap.
+    pub fn validate_sequence_number(&mut self, received: UInt32) -> Result<(), StatusCode> {
+        let valid = match self.last_received_sequence_number {
+            Some(n) if n >= SEQUENCE_NUMBER_WRAP_AROUND => received < SEQUENCE_NUMBER_WRAP_AROUND_LIMIT,
+            Some(n) => received == n + 1,
+            None => true,
+        };
+        if valid {
+            self.last_received_sequence_number = Some(received);
+            Ok(())
+        } else {
+            error!("Sequence number {} is invalid, last received was {:?}", received, self.last_received_sequence_number);
+            Err(BAD_SEQUENCE_NUMBER_INVALID)
+        }
     }
 
     /// Test if the token has expired yet
@@ -141,7 +613,7 @@ impl SecureChannel {
     }
 
     pub fn symmetric_signature_size(&self) -> usize {
-        if self.security_policy != SecurityPolicy::None {
+        if !self.security_policy.is_none() {
             self.security_policy.symmetric_signature_size()
         } else {
             0
@@ -152,7 +624,7 @@ impl SecureChannel {
     ///
     /// Padding adds bytes to the body to make it a multiple of the block size so it can be encrypted.
     pub fn calc_chunk_padding(&self, bytes_to_write: usize, security_header: &SecurityHeader, message_chunk_size: usize) -> usize {
-        if self.security_policy != SecurityPolicy::None && self.security_mode != MessageSecurityMode::None {
+        if !self.security_policy.is_none() && self.security_mode != MessageSecurityMode::None {
             // Signature size comes from policy
             let signature_size = self.security_policy.symmetric_signature_size();
             // Plain text block size comes from policy
@@ -189,42 +661,37 @@ impl SecureChannel {
     /// Sign the following block
     fn sign(&self, src: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
         debug!("Producing signature for {} bytes of data into signature of {} bytes", src.len(), signature.len());
-        let key = &(self.keys.as_ref().unwrap()).0;
-        match self.security_policy {
-            SecurityPolicy::Basic128Rsa15 => {
-                // HMAC SHA-1
-                hash::hmac_sha1(key, src, signature)
-            }
-            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {
-                // HMAC SHA-256                
-                hash::hmac_sha256(key, src, signature)
-            }
-            _ => {
-                panic!("Unsupported policy")
-            }
+        if self.security_policy.is_ecc() {
+            // ECC policies sign every chunk with the channel's long-term EC key pair
+            let private_key = self.private_key.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+            let ecdsa_signature = self.security_policy.ecdsa_sign(private_key, src)?;
+            signature.copy_from_slice(&ecdsa_signature);
+            Ok(())
+        } else {
+            let key = &(self.keys.as_ref().unwrap()).0;
+            self.security_policy.symmetric_sign(key, src, signature)
         }
     }
 
     /// Verify their signature
     fn verify(&self, src: &[u8], signature: &[u8]) -> Result<(), StatusCode> {
-        let key = &(self.their_keys.as_ref().unwrap()).0;
-        // Verify the signature using SHA-1 / SHA-256 HMAC
-        let verified = match self.security_policy {
-            SecurityPolicy::Basic128Rsa15 => {
-                // HMAC SHA-1
-                hash::verify_hmac_sha1(key, src, signature)
-            }
-            SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {
-                // HMAC SHA-256                
-                hash::verify_hmac_sha256(key, src, signature)
-            }
-            _ => {
-                panic!("Unsupported policy")
+        #[cfg(feature = "testing")]
+        {
+            if let Some(status_code) = testing::verify_failure() {
+                return Err(status_code);
             }
+        }
+        let verified = if self.security_policy.is_ecc() {
+            let their_cert = self.their_cert.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+            self.security_policy.ecdsa_verify(their_cert, src, signature)?
+        } else {
+            let key = &(self.their_keys.as_ref().unwrap()).0;
+            // Verify the signature using the policy's symmetric signature algorithm
+            self.security_policy.symmetric_verify(key, src, signature)?
         };
         if verified {
             Ok(())
-        } else { 
+        } else {
             error!("Signature invalid {:?}", signature);
             Err(BAD_APPLICATION_SIGNATURE_INVALID)
         }
@@ -233,48 +700,130 @@ impl SecureChannel {
     /// Encrypt the data
     fn encrypt(&self, src: &[u8], dst: &mut [u8]) -> Result<(), StatusCode> {
         let keys = self.keys.as_ref().unwrap();
-        let key = &keys.1;
-        let iv = &keys.2;
-        let result = key.encrypt(src, iv, dst);
-        if result.is_ok() {
-            Ok(())
-        } else {
-            error!("Cannot encrypt data, {}", result.unwrap_err());
-            Err(BAD_ENCODING_ERROR)
-        }
+        self.security_policy.symmetric_encrypt(&keys.1, &keys.2, src, dst)
     }
 
     /// Decrypt the data
     fn decrypt(&self, src: &[u8], dst: &mut [u8]) -> Result<(), StatusCode> {
+        #[cfg(feature = "testing")]
+        {
+            if let Some(status_code) = testing::decrypt_failure() {
+                return Err(status_code);
+            }
+        }
         let keys = self.their_keys.as_ref().unwrap();
-        let key = &keys.1;
-        let iv = &keys.2;
-        let result = key.decrypt(src, iv, dst);
-        if result.is_ok() {
+        self.security_policy.symmetric_decrypt(&keys.1, &keys.2, src, dst)
+    }
+
+    /// Sign the following block using our private key (RSA, or EC for the ECC policies)
+    fn asymmetric_sign(&self, src: &[u8], signature: &mut [u8]) -> Result<(), StatusCode> {
+        let private_key = self.private_key.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+        self.security_policy.asymmetric_sign(private_key, src, signature)
+    }
+
+    /// Verify their signature using their X509 certificate's public key (RSA, or EC for the ECC
+    /// policies)
+    fn asymmetric_verify(&self, src: &[u8], signature: &[u8]) -> Result<(), StatusCode> {
+        let their_cert = self.their_cert.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+        let verified = self.security_policy.asymmetric_verify(their_cert, src, signature)?;
+        if verified {
             Ok(())
         } else {
-            error!("Cannot decrypt data, {}", result.unwrap_err());
-            Err(BAD_DECODING_ERROR)
+            error!("Asymmetric signature invalid {:?}", signature);
+            Err(BAD_APPLICATION_SIGNATURE_INVALID)
         }
     }
 
-    // Panic code which requires a policy
-    fn expect_supported_security_policy(&self) {
-        match self.security_policy {
-            SecurityPolicy::Basic128Rsa15 | SecurityPolicy::Basic256 | SecurityPolicy::Basic256Sha256 => {}
-            _ => {
-                panic!("Unsupported security policy");
-            }
+    /// Encrypt the data using their X509 certificate's RSA public key. Per Part 6, the data is
+    /// processed in RSA-modulus-sized blocks: each `asymmetric_plain_text_block_size` chunk of
+    /// `src` is encrypted into one `asymmetric_cipher_text_block_size` block written
+    /// consecutively into `dst`, which must be large enough to hold the expanded ciphertext.
+    fn asymmetric_encrypt(&self, src: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
+        let their_cert = self.their_cert.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+        let private_key = self.private_key.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+        let plain_text_block_size = self.security_policy.asymmetric_plain_text_block_size(private_key);
+        let cipher_text_block_size = self.security_policy.asymmetric_cipher_text_block_size(private_key);
+
+        let mut src_pos = 0;
+        let mut dst_pos = 0;
+        while src_pos < src.len() {
+            let block_len = usize::min(plain_text_block_size, src.len() - src_pos);
+            let written = self.security_policy.asymmetric_encrypt(their_cert, &src[src_pos..src_pos + block_len], &mut dst[dst_pos..dst_pos + cipher_text_block_size])?;
+            src_pos += block_len;
+            dst_pos += written;
         }
+        Ok(dst_pos)
+    }
+
+    /// Decrypt the data using our RSA private key, the inverse of `asymmetric_encrypt`: `src` is
+    /// consumed in `asymmetric_cipher_text_block_size` blocks, each producing up to
+    /// `asymmetric_plain_text_block_size` bytes of plain text in `dst`.
+    fn asymmetric_decrypt(&self, src: &[u8], dst: &mut [u8]) -> Result<usize, StatusCode> {
+        let private_key = self.private_key.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+        let plain_text_block_size = self.security_policy.asymmetric_plain_text_block_size(private_key);
+        let cipher_text_block_size = self.security_policy.asymmetric_cipher_text_block_size(private_key);
+
+        let mut src_pos = 0;
+        let mut dst_pos = 0;
+        while src_pos < src.len() {
+            let block_len = usize::min(cipher_text_block_size, src.len() - src_pos);
+            let written = self.security_policy.asymmetric_decrypt(private_key, &src[src_pos..src_pos + block_len], &mut dst[dst_pos..dst_pos + plain_text_block_size])?;
+            src_pos += block_len;
+            dst_pos += written;
+        }
+        Ok(dst_pos)
+    }
+
+    /// Number of ciphertext bytes produced when RSA-encrypting `plain_len` bytes of plain text in
+    /// policy/key-sized blocks, i.e. the buffer size `asymmetric_encrypt` needs for its `dst`
+    fn asymmetric_encrypted_len(&self, plain_len: usize) -> Result<usize, StatusCode> {
+        let private_key = self.private_key.as_ref().ok_or(BAD_CERTIFICATE_INVALID)?;
+        let plain_text_block_size = self.security_policy.asymmetric_plain_text_block_size(private_key);
+        let cipher_text_block_size = self.security_policy.asymmetric_cipher_text_block_size(private_key);
+        let num_blocks = (plain_len + plain_text_block_size - 1) / plain_text_block_size;
+        Ok(num_blocks * cipher_text_block_size)
+    }
+
+    /// Calculate the padding size for an asymmetric (OpenSecureChannel) chunk. This mirrors
+    /// `calc_chunk_padding` but works in RSA modulus-sized blocks derived from our private key
+    /// rather than the symmetric cipher block size.
+    pub fn calc_chunk_padding_asymmetric(&self, bytes_to_write: usize, message_chunk_size: usize) -> usize {
+        if self.security_policy.is_none() || self.security_mode == MessageSecurityMode::None {
+            return 0;
+        }
+        let private_key = match self.private_key.as_ref() {
+            Some(private_key) => private_key,
+            None => return 0,
+        };
+        let signature_size = self.security_policy.asymmetric_signature_size(private_key);
+        let plain_text_block_size = self.security_policy.asymmetric_plain_text_block_size(private_key);
+        let cipher_text_block_size = self.security_policy.asymmetric_cipher_text_block_size(private_key);
+
+        let max_body_size = if message_chunk_size != 0 {
+            let header_size = MESSAGE_CHUNK_HEADER_SIZE + AsymmetricSecurityHeader::none().byte_len();
+            let sequence_header_size = SEQUENCE_HEADER_SIZE;
+            let f1: f64 = (message_chunk_size - header_size - signature_size - 1) as f64;
+            let f2: f64 = cipher_text_block_size as f64;
+            plain_text_block_size * ((f1 / f2).floor() as usize) - sequence_header_size
+        } else {
+            0
+        };
+        let padding_size = if max_body_size > 0 && bytes_to_write > max_body_size {
+            0
+        } else {
+            plain_text_block_size - ((bytes_to_write + signature_size + 1) % plain_text_block_size)
+        };
+        debug!("Asymmetric padding calculated to be {} bytes", padding_size);
+        padding_size
     }
 
     pub fn signing_enabled(&self) -> bool {
-        self.security_policy != SecurityPolicy::None && self.security_mode == MessageSecurityMode::Sign
+        !self.security_policy.is_none() && self.security_mode == MessageSecurityMode::Sign
     }
 
-    /// Test if encryption is enabled. 
+    /// Test if encryption is enabled.
     pub fn encryption_enabled(&self) -> bool {
-        self.security_policy != SecurityPolicy::None && self.security_mode == MessageSecurityMode::SignAndEncrypt
+        !self.security_policy.is_none() && self.security_mode == MessageSecurityMode::SignAndEncrypt
     }
 
     /// Encode data using security. Destination buffer is expected to be same size as src and expected
@@ -282,56 +831,96 @@ impl SecureChannel {
     ///
     /// Signing is done first and then encryption
     ///
+    /// Note: an ECC security policy has no ECIES/ECDH key transport, so an `OpenSecureChannel`
+    /// chunk under `MessageSecurityMode::SignAndEncrypt` fails with `BAD_SECURITY_POLICY_REJECTED`
+    /// for those policies; only `MessageSecurityMode::Sign` is usable with them.
+    ///
+    /// For an `OpenSecureChannel` chunk under `MessageSecurityMode::SignAndEncrypt` with an RSA
+    /// policy, `dst` must instead be large enough for the RSA-expanded ciphertext, which is
+    /// written from `e_from` and is `asymmetric_encrypted_len(e_to - e_from)` bytes long.
+    ///
     /// S - Message Header
     /// S - Security Header
     /// S - Sequence Header - E
     /// S - Body            - E
     /// S - Padding         - E
     ///     Signature       - E
-    pub fn encrypt_and_sign(&self, src: &[u8], sign_info: (usize, usize), encrypt_info: (usize, usize), dst: &mut [u8]) -> Result<(), StatusCode> {
+    pub fn encrypt_and_sign(&mut self, message_type: MessageChunkType, src: &[u8], sign_info: (usize, usize), encrypt_info: (usize, usize), dst: &mut [u8]) -> Result<(), StatusCode> {
         let (s_from, s_to) = sign_info;
         let (e_from, e_to) = encrypt_info;
+        let asymmetric = message_type == MessageChunkType::OpenSecureChannel;
+
+        // Stamp the next sequence number into the sequence header before signing, so the
+        // signature (and, for SignAndEncrypt, the ciphertext) covers the value the peer will
+        // actually validate in decrypt_and_verify.
+        let sequence_number = self.next_sequence_number();
+
         match self.security_mode {
             MessageSecurityMode::None => {
                 debug!("encrypt_and_sign is doing nothing because security mode == None");
                 // Just copy data to out
                 dst.copy_from_slice(src);
+                write_sequence_number(dst, e_from, sequence_number);
                 Ok(())
             }
             MessageSecurityMode::Sign => {
                 debug!("encrypt_and_sign security mode == Sign");
-                self.expect_supported_security_policy();
                 let signature_len = src.len() - s_to;
                 let mut signature = vec![0u8; signature_len];
                 debug!("signature len = {}", signature_len);
-                // Sign the message header, security header, sequence header, body, padding
-                self.sign(&src[s_from..s_to], &mut signature)?;
                 &dst[..s_to].copy_from_slice(&src[..s_to]);
+                write_sequence_number(dst, e_from, sequence_number);
+                // Sign the message header, security header, sequence header, body, padding
+                if asymmetric {
+                    self.asymmetric_sign(&dst[s_from..s_to], &mut signature)?;
+                } else {
+                    self.sign(&dst[s_from..s_to], &mut signature)?;
+                }
                 debug!("Signature = {:?}", signature);
                 &dst[s_to..].copy_from_slice(&signature);
                 Ok(())
             }
             MessageSecurityMode::SignAndEncrypt => {
                 debug!("encrypt_and_sign security mode == SignAndEncrypt");
-                self.expect_supported_security_policy();
 
-                // There is an expectation that the block is padded so, this is a quick test
-                if (e_to - e_from) % 16 != 0 {
+                // There is an expectation that the block is padded so, this is a quick test.
+                // For the asymmetric (RSA) path this must be the plain text block size, i.e.
+                // the modulus size minus the policy's PKCS1/OAEP overhead, to match the padding
+                // that calc_chunk_padding_asymmetric actually produced.
+                let block_size = if asymmetric {
+                    self.private_key.as_ref().map_or(16, |k| self.security_policy.asymmetric_plain_text_block_size(k))
+                } else {
+                    16
+                };
+                if (e_to - e_from) % block_size != 0 {
                     error!("The plain text block is not padded properly, size = {}", e_to - e_from);
                     return Err(BAD_DECODING_ERROR);
                 }
 
-                let mut dst_tmp = vec![0u8; dst.len() + 16]; // tmp includes +16 for blocksize
+                let mut dst_tmp = vec![0u8; dst.len() + block_size]; // tmp includes +1 block for padding
+                &dst_tmp[..s_to].copy_from_slice(&src[..s_to]);
+                write_sequence_number(&mut dst_tmp, e_from, sequence_number);
+
                 let signature_len = src.len() - s_to;
                 debug!("signature len = {}", signature_len);
                 let mut signature = vec![0u8; signature_len];
                 // Sign the message header, security header, sequence header, body, padding
-                self.sign(&src[s_from..s_to], &mut signature)?;
-                &dst_tmp[..s_to].copy_from_slice(&src[..s_to]);
+                if asymmetric {
+                    self.asymmetric_sign(&dst_tmp[s_from..s_to], &mut signature)?;
+                } else {
+                    self.sign(&dst_tmp[s_from..s_to], &mut signature)?;
+                }
                 &dst_tmp[s_to..].copy_from_slice(&signature);
 
-                // Encrypt the sequence header, payload, signature
-                self.encrypt(&dst_tmp[e_from..e_to], &mut dst[e_from..e_to])?;
+                // Encrypt the sequence header, payload, signature. The RSA path expands each
+                // plain text block into a full modulus-size block, so dst must be sized (by the
+                // caller) for the expanded ciphertext, not just src.len().
+                if asymmetric {
+                    let cipher_len = self.asymmetric_encrypted_len(e_to - e_from)?;
+                    self.asymmetric_encrypt(&dst_tmp[e_from..e_to], &mut dst[e_from..e_from + cipher_len])?;
+                } else {
+                    self.encrypt(&dst_tmp[e_from..e_to], &mut dst[e_from..e_to])?;
+                }
                 // Copy the message header / security header
                 &dst[..e_from].copy_from_slice(&dst_tmp[..e_from]);
 
@@ -351,32 +940,43 @@ impl SecureChannel {
     /// S - Body            - E
     /// S - Padding         - E
     ///     Signature       - E
-    pub fn decrypt_and_verify(&self, src: &[u8], sign_info: (usize, usize), encrypt_info: (usize, usize), dst: &mut [u8]) -> Result<(), StatusCode> {
+    pub fn decrypt_and_verify(&mut self, message_type: MessageChunkType, src: &[u8], sign_info: (usize, usize), encrypt_info: (usize, usize), dst: &mut [u8]) -> Result<(), StatusCode> {
         let (s_from, s_to) = sign_info;
         let (e_from, e_to) = encrypt_info;
+        let asymmetric = message_type == MessageChunkType::OpenSecureChannel;
         match self.security_mode {
             MessageSecurityMode::None => {
                 // Copy everything
                 let len = src.len();
                 &dst[..len].copy_from_slice(&src[..len]);
+                self.validate_sequence_number(read_sequence_number(dst, e_from))?;
                 Ok(())
             }
             MessageSecurityMode::Sign => {
-                self.expect_supported_security_policy();
                 // Copy everything
                 let len = src.len();
                 debug!("copying from slice ..{}", len);
                 &dst[..len].copy_from_slice(&src[..len]);
+                self.validate_sequence_number(read_sequence_number(dst, e_from))?;
                 // Verify signature
                 debug!("Verifying range from {}..{} to signature {}..", s_from, s_to, s_to);
-                self.verify(&dst[s_from..s_to], &dst[s_to..])?;
+                if asymmetric {
+                    self.asymmetric_verify(&dst[s_from..s_to], &dst[s_to..])?;
+                } else {
+                    self.verify(&dst[s_from..s_to], &dst[s_to..])?;
+                }
                 Ok(())
             }
             MessageSecurityMode::SignAndEncrypt => {
-                self.expect_supported_security_policy();
-
-                // There is an expectation that the block is padded so, this is a quick test
-                if (e_to - e_from) % 16 != 0 {
+                // There is an expectation that the block is padded so, this is a quick test.
+                // For the asymmetric (RSA) path this must be the plain text block size, to match
+                // the padding that calc_chunk_padding_asymmetric actually produced.
+                let block_size = if asymmetric {
+                    self.private_key.as_ref().map_or(16, |k| self.security_policy.asymmetric_plain_text_block_size(k))
+                } else {
+                    16
+                };
+                if (e_to - e_from) % block_size != 0 {
                     error!("The plain text block is not padded properly, size = {}", e_to - e_from);
                     return Err(BAD_DECODING_ERROR);
                 }
@@ -384,13 +984,28 @@ impl SecureChannel {
                 // Copy security header
                 &dst[..e_from].copy_from_slice(&src[..e_from]);
 
-                // Decrypt encrypted portion
-                let mut decrypted_tmp = vec![0u8; e_to - e_from + 16]; // tmp includes +16 for blocksize
-                self.decrypt(&src[e_from..e_to], &mut decrypted_tmp)?;
+                // Decrypt encrypted portion. The RSA path reads modulus-sized ciphertext blocks
+                // that are larger than the plain text they decode to, so the slice read from
+                // `src` is the expanded cipher length, not `e_to - e_from`.
+                let mut decrypted_tmp = vec![0u8; e_to - e_from + block_size]; // tmp includes +1 block
+                if asymmetric {
+                    let cipher_len = self.asymmetric_encrypted_len(e_to - e_from)?;
+                    self.asymmetric_decrypt(&src[e_from..e_from + cipher_len], &mut decrypted_tmp)?;
+                } else {
+                    self.decrypt(&src[e_from..e_to], &mut decrypted_tmp)?;
+                }
                 &dst[e_from..e_to].copy_from_slice(&decrypted_tmp[..(e_to - e_from)]);
 
+                // The sequence number lives at the front of the encrypted range, so it is only
+                // readable from dst once decryption above has run.
+                self.validate_sequence_number(read_sequence_number(dst, e_from))?;
+
                 // Verify signature (after encrypted portion)
-                self.verify(&dst[s_from..s_to], &dst[s_to..])?;
+                if asymmetric {
+                    self.asymmetric_verify(&dst[s_from..s_to], &dst[s_to..])?;
+                } else {
+                    self.verify(&dst[s_from..s_to], &dst[s_to..])?;
+                }
                 Ok(())
             }
             MessageSecurityMode::Invalid => {
@@ -400,3 +1015,100 @@ impl SecureChannel {
         }
     }
 }
+
+impl Drop for SecureChannel {
+    fn drop(&mut self) {
+        // Nonces and derived key material are secrets that should not linger in freed heap
+        // memory once the channel goes away.
+        self.nonce.zeroize();
+        self.their_nonce.zeroize();
+        self.zeroize_keys();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_sequence_number_wraps_at_boundary() {
+        let mut channel = SecureChannel::new();
+
+        channel.last_sent_sequence_number = Some(SEQUENCE_NUMBER_WRAP_AROUND - 1);
+        assert_eq!(channel.next_sequence_number(), SEQUENCE_NUMBER_WRAP_AROUND);
+
+        // The next value would exceed the boundary, so it wraps instead of being returned as-is
+        assert_eq!(channel.next_sequence_number(), 1);
+    }
+
+    #[test]
+    fn validate_sequence_number_accepts_the_wrapped_value() {
+        let mut channel = SecureChannel::new();
+        channel.last_received_sequence_number = Some(SEQUENCE_NUMBER_WRAP_AROUND);
+
+        // Anything below the wrap-around limit is accepted once the previous number is at or
+        // above the boundary ...
+        assert!(channel.validate_sequence_number(1).is_ok());
+
+        // ... but a value that merely continues counting up past the boundary is not
+        channel.last_received_sequence_number = Some(SEQUENCE_NUMBER_WRAP_AROUND);
+        assert_eq!(channel.validate_sequence_number(SEQUENCE_NUMBER_WRAP_AROUND + 1), Err(BAD_SEQUENCE_NUMBER_INVALID));
+    }
+
+    #[test]
+    fn asymmetric_rsa_round_trip() {
+        let mut channel = SecureChannel::new();
+        channel.security_policy = Box::new(SecurityPolicy::Basic256Sha256);
+
+        // A throwaway key pair and self-signed certificate, used only to exercise the RSA block
+        // chunking in asymmetric_encrypt/asymmetric_decrypt.
+        let private_key = PKey::new_rsa(2048).expect("generate test RSA key");
+        let cert = X509::new_self_signed(&private_key).expect("create test certificate");
+        channel.private_key = Some(private_key);
+        channel.their_cert = Some(cert);
+
+        // Longer than a single RSA block, so this only round-trips if the block loop in
+        // asymmetric_encrypt/asymmetric_decrypt chunks and reassembles correctly.
+        let plain_text = vec![0x5au8; 300];
+        let cipher_len = channel.asymmetric_encrypted_len(plain_text.len()).unwrap();
+        let mut cipher_text = vec![0u8; cipher_len];
+        let written = channel.asymmetric_encrypt(&plain_text, &mut cipher_text).unwrap();
+        assert_eq!(written, cipher_len);
+
+        let mut decrypted = vec![0u8; cipher_len];
+        let decrypted_len = channel.asymmetric_decrypt(&cipher_text, &mut decrypted).unwrap();
+        assert_eq!(&decrypted[..decrypted_len], plain_text.as_slice());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn verify_failure_hook_overrides_result() {
+        let mut channel = SecureChannel::new();
+        channel.security_mode = MessageSecurityMode::Sign;
+
+        let src = vec![0u8; 40];
+        let mut dst = vec![0u8; 40];
+
+        testing::set_verify_failure(Some(BAD_APPLICATION_SIGNATURE_INVALID));
+        let result = channel.decrypt_and_verify(MessageChunkType::Message, &src, (0, 24), (0, 24), &mut dst);
+        testing::set_verify_failure(None);
+
+        assert_eq!(result, Err(BAD_APPLICATION_SIGNATURE_INVALID));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn decrypt_failure_hook_overrides_result() {
+        let mut channel = SecureChannel::new();
+        channel.security_mode = MessageSecurityMode::SignAndEncrypt;
+
+        let src = vec![0u8; 40];
+        let mut dst = vec![0u8; 40];
+
+        testing::set_decrypt_failure(Some(BAD_DECODING_ERROR));
+        let result = channel.decrypt_and_verify(MessageChunkType::Message, &src, (0, 24), (8, 24), &mut dst);
+        testing::set_decrypt_failure(None);
+
+        assert_eq!(result, Err(BAD_DECODING_ERROR));
+    }
+}